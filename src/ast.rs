@@ -1,18 +1,22 @@
-#[derive(Debug, Clone, PartialEq)]
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Expr {
     FunctionCall(FunctionCall),
+    Identifier(String),
     Literal(Literal),
     // FunctionDefinition(FunctionDefinition),
     // IfThenElse(IfThenElse),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct FunctionCall {
     pub name: String,
     pub args: Vec<Expr>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub enum Literal {
     String(String),
     Integer(i64),
@@ -20,6 +24,191 @@ pub enum Literal {
     Boolean(bool),
 }
 
+pub const DEFAULT_FLOAT_EPSILON: f64 = 1e-9;
+
+// `Eq` is required to use literals (and expressions) as map keys. It holds
+// because `PartialEq` compares floats by `float_key`, under which NaN equals
+// itself.
+impl Eq for Literal {}
+
+// Floats are compared by `float_key` so that equality agrees with `Hash` and
+// `Ord`: all NaNs are equal to each other, and 0.0 equals -0.0. Use
+// `equivalent_within` to compare floats numerically.
+impl PartialEq for Literal {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Literal::String(a), Literal::String(b)) => a == b,
+            (Literal::Integer(a), Literal::Integer(b)) => a == b,
+            (Literal::Float(a), Literal::Float(b)) => float_key(*a) == float_key(*b),
+            (Literal::Boolean(a), Literal::Boolean(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Hash for Literal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Literal::String(s) => s.hash(state),
+            Literal::Integer(i) => i.hash(state),
+            Literal::Float(f) => float_key(*f).hash(state),
+            Literal::Boolean(b) => b.hash(state),
+        }
+    }
+}
+
+// The bits of `f`, with every NaN mapped to one NaN and -0.0 to 0.0.
+fn float_key(f: f64) -> u64 {
+    if f.is_nan() {
+        f64::NAN.to_bits()
+    } else if f == 0.0 {
+        0.0f64.to_bits()
+    } else {
+        f.to_bits()
+    }
+}
+
+// Total order across literal types: strings, then booleans (false before
+// true), then integers, then floats. Floats are ordered numerically with NaN
+// last; integers and floats are not compared numerically.
+impl Ord for Literal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Literal::String(a), Literal::String(b)) => a.cmp(b),
+            (Literal::Boolean(a), Literal::Boolean(b)) => a.cmp(b),
+            (Literal::Integer(a), Literal::Integer(b)) => a.cmp(b),
+            (Literal::Float(a), Literal::Float(b)) => a
+                .partial_cmp(b)
+                .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan())),
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+}
+
+impl PartialOrd for Literal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Literal {
+    fn type_rank(&self) -> u8 {
+        match self {
+            Literal::String(_) => 0,
+            Literal::Boolean(_) => 1,
+            Literal::Integer(_) => 2,
+            Literal::Float(_) => 3,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Literal::String(_) => "String",
+            Literal::Integer(_) => "Integer",
+            Literal::Float(_) => "Float",
+            Literal::Boolean(_) => "Boolean",
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Literal::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Literal::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Literal::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Literal::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    // Integer operations stay integral and fail on overflow; mixing an integer
+    // with a float promotes to float. Any other combination is a type error.
+    fn checked_arithmetic(
+        self,
+        other: Literal,
+        op: &str,
+        int_op: fn(i64, i64) -> Option<i64>,
+        float_op: fn(f64, f64) -> f64,
+    ) -> Result<Literal, String> {
+        match (self, other) {
+            (Literal::Integer(l), Literal::Integer(r)) => int_op(l, r)
+                .map(Literal::Integer)
+                .ok_or_else(|| format!("integer overflow in {} {} {}", l, op, r)),
+            (Literal::Integer(l), Literal::Float(r)) => Ok(Literal::Float(float_op(l as f64, r))),
+            (Literal::Float(l), Literal::Integer(r)) => Ok(Literal::Float(float_op(l, r as f64))),
+            (Literal::Float(l), Literal::Float(r)) => Ok(Literal::Float(float_op(l, r))),
+            (l, r) => Err(format!(
+                "cannot apply '{}' to {} and {}",
+                op,
+                l.type_name(),
+                r.type_name()
+            )),
+        }
+    }
+
+    pub fn checked_add(self, other: Literal) -> Result<Literal, String> {
+        self.checked_arithmetic(other, "+", i64::checked_add, |l, r| l + r)
+    }
+
+    pub fn checked_sub(self, other: Literal) -> Result<Literal, String> {
+        self.checked_arithmetic(other, "-", i64::checked_sub, |l, r| l - r)
+    }
+
+    pub fn checked_mul(self, other: Literal) -> Result<Literal, String> {
+        self.checked_arithmetic(other, "*", i64::checked_mul, |l, r| l * r)
+    }
+
+    /// Integer division truncates toward zero. Dividing by an integer or float
+    /// zero is an error rather than producing infinity or NaN.
+    pub fn checked_div(self, other: Literal) -> Result<Literal, String> {
+        if matches!(other, Literal::Integer(0)) || other.as_float() == Some(0.0) {
+            return Err("division by zero".to_string());
+        }
+        self.checked_arithmetic(other, "/", i64::checked_div, |l, r| l / r)
+    }
+
+    /// Integer powers with a non-negative exponent stay integral; a negative
+    /// exponent or a float operand uses float exponentiation. Raising zero to a
+    /// negative power divides by zero, so it is an error like `checked_div`.
+    pub fn checked_pow(self, other: Literal) -> Result<Literal, String> {
+        let zero_base = matches!(self, Literal::Integer(0)) || self.as_float() == Some(0.0);
+        let negative_exponent = matches!(other, Literal::Integer(r) if r < 0)
+            || other.as_float().is_some_and(|r| r < 0.0);
+        if zero_base && negative_exponent {
+            return Err("division by zero".to_string());
+        }
+        match (self, other) {
+            (Literal::Integer(l), Literal::Integer(r)) if r < 0 => {
+                Ok(Literal::Float((l as f64).powf(r as f64)))
+            }
+            (l, r) => l.checked_arithmetic(
+                r,
+                "^",
+                |l, r| u32::try_from(r).ok().and_then(|r| l.checked_pow(r)),
+                f64::powf,
+            ),
+        }
+    }
+}
+
 impl From<String> for Literal {
     fn from(value: String) -> Self {
         Literal::String(value)
@@ -43,3 +232,405 @@ impl From<i64> for Literal {
         Literal::Integer(value)
     }
 }
+
+impl FunctionCall {
+    pub fn new(name: impl Into<String>, args: impl IntoIterator<Item = Expr>) -> Self {
+        FunctionCall {
+            name: name.into(),
+            args: args.into_iter().collect(),
+        }
+    }
+
+    // Helpers for passes that rewrite a call's arguments but keep its name.
+
+    pub fn with_name(self, name: impl Into<String>) -> Self {
+        FunctionCall {
+            name: name.into(),
+            args: self.args,
+        }
+    }
+
+    pub fn map_args(self, f: impl FnMut(Expr) -> Expr) -> Self {
+        FunctionCall::new(self.name, self.args.into_iter().map(f))
+    }
+
+    pub fn filter_args(self, mut predicate: impl FnMut(&Expr) -> bool) -> Self {
+        FunctionCall::new(
+            self.name,
+            self.args.into_iter().filter(|arg| predicate(arg)),
+        )
+    }
+
+    pub fn flat_map_args(self, f: impl FnMut(Expr) -> Vec<Expr>) -> Self {
+        FunctionCall::new(self.name, self.args.into_iter().flat_map(f))
+    }
+
+    /// Like `map_args`, but stops at the first argument `f` fails on.
+    pub fn try_map_args<E>(self, f: impl FnMut(Expr) -> Result<Expr, E>) -> Result<Self, E> {
+        let args = self
+            .args
+            .into_iter()
+            .map(f)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(FunctionCall::new(self.name, args))
+    }
+}
+
+impl Expr {
+    pub fn call(name: impl Into<String>, args: impl IntoIterator<Item = Expr>) -> Self {
+        Expr::FunctionCall(FunctionCall::new(name, args))
+    }
+
+    // Both metrics walk the tree with an explicit stack so that pathologically
+    // nested input cannot overflow the native stack.
+    pub fn depth(&self) -> usize {
+        let mut max_depth = 0;
+        let mut stack = vec![(self, 1)];
+        while let Some((expr, depth)) = stack.pop() {
+            max_depth = max_depth.max(depth);
+            if let Expr::FunctionCall(call) = expr {
+                stack.extend(call.args.iter().map(|arg| (arg, depth + 1)));
+            }
+        }
+        max_depth
+    }
+
+    pub fn node_count(&self) -> usize {
+        let mut count = 0;
+        let mut stack = vec![self];
+        while let Some(expr) = stack.pop() {
+            count += 1;
+            if let Expr::FunctionCall(call) = expr {
+                stack.extend(call.args.iter());
+            }
+        }
+        count
+    }
+
+    // Semantic comparison, as opposed to the exact `PartialEq`, which compares
+    // floats bit for bit after mapping every NaN to one NaN and -0.0 to 0.0.
+    // Here floats are compared with `DEFAULT_FLOAT_EPSILON` instead.
+    // Function call arguments are compared positionally, since no call is
+    // assumed to be commutative.
+    pub fn equivalent(&self, other: &Expr) -> bool {
+        self.equivalent_within(other, DEFAULT_FLOAT_EPSILON)
+    }
+
+    pub fn equivalent_within(&self, other: &Expr, epsilon: f64) -> bool {
+        match (self, other) {
+            (Expr::FunctionCall(a), Expr::FunctionCall(b)) => {
+                a.name == b.name
+                    && a.args.len() == b.args.len()
+                    && a.args
+                        .iter()
+                        .zip(&b.args)
+                        .all(|(x, y)| x.equivalent_within(y, epsilon))
+            }
+            (Expr::Identifier(a), Expr::Identifier(b)) => a == b,
+            (Expr::Literal(a), Expr::Literal(b)) => a.equivalent_within(b, epsilon),
+            _ => false,
+        }
+    }
+}
+
+impl Literal {
+    pub fn equivalent(&self, other: &Literal) -> bool {
+        self.equivalent_within(other, DEFAULT_FLOAT_EPSILON)
+    }
+
+    pub fn equivalent_within(&self, other: &Literal, epsilon: f64) -> bool {
+        match (self, other) {
+            (Literal::Float(a), Literal::Float(b)) => {
+                (a.is_nan() && b.is_nan()) || a == b || (a - b).abs() <= epsilon
+            }
+            _ => self == other,
+        }
+    }
+}
+
+impl From<FunctionCall> for Expr {
+    fn from(value: FunctionCall) -> Self {
+        Expr::FunctionCall(value)
+    }
+}
+
+impl From<Literal> for Expr {
+    fn from(value: Literal) -> Self {
+        Expr::Literal(value)
+    }
+}
+
+impl From<String> for Expr {
+    fn from(value: String) -> Self {
+        Expr::Literal(value.into())
+    }
+}
+
+impl From<bool> for Expr {
+    fn from(value: bool) -> Self {
+        Expr::Literal(value.into())
+    }
+}
+
+impl From<f64> for Expr {
+    fn from(value: f64) -> Self {
+        Expr::Literal(value.into())
+    }
+}
+
+impl From<i64> for Expr {
+    fn from(value: i64) -> Self {
+        Expr::Literal(value.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_metrics_of_literal() {
+        let expr = Expr::from(42);
+        assert_eq!(expr.depth(), 1);
+        assert_eq!(expr.node_count(), 1);
+    }
+
+    #[test]
+    fn test_metrics_of_nested_calls() {
+        // f(1, g(2, h()), "x")
+        let expr = Expr::call(
+            "f",
+            [
+                Expr::from(1),
+                Expr::call("g", [Expr::from(2), Expr::call("h", [])]),
+                Expr::from("x".to_string()),
+            ],
+        );
+        assert_eq!(expr.depth(), 3);
+        assert_eq!(expr.node_count(), 6);
+    }
+
+    #[test]
+    fn test_metrics_of_deep_chain() {
+        let mut expr = Expr::from(0);
+        for _ in 0..1_000 {
+            expr = Expr::call("f", [expr]);
+        }
+        assert_eq!(expr.depth(), 1_001);
+        assert_eq!(expr.node_count(), 1_001);
+    }
+
+    #[test]
+    fn test_transform_args() {
+        let call = FunctionCall::new("f", [Expr::from(1), Expr::from(true), Expr::from(2)]);
+
+        assert_eq!(
+            call.clone().map_args(|arg| match arg {
+                Expr::Literal(Literal::Integer(n)) => Expr::from(n * 10),
+                arg => arg,
+            }),
+            FunctionCall::new("f", [Expr::from(10), Expr::from(true), Expr::from(20)])
+        );
+        assert_eq!(
+            call.clone()
+                .filter_args(|arg| !matches!(arg, Expr::Literal(Literal::Boolean(_)))),
+            FunctionCall::new("f", [Expr::from(1), Expr::from(2)])
+        );
+        assert_eq!(
+            call.clone().flat_map_args(|arg| match arg {
+                Expr::Literal(Literal::Boolean(_)) => vec![],
+                arg => vec![arg.clone(), arg],
+            }),
+            FunctionCall::new("f", [1, 1, 2, 2].map(Expr::from))
+        );
+        assert_eq!(
+            call.with_name("g"),
+            FunctionCall::new("g", [Expr::from(1), Expr::from(true), Expr::from(2)])
+        );
+    }
+
+    #[test]
+    fn test_try_map_args() {
+        let negate = |arg: Expr| match arg {
+            Expr::Literal(Literal::Integer(n)) => Ok(Expr::from(-n)),
+            other => Err(format!("not an integer: {:?}", other)),
+        };
+
+        assert_eq!(
+            FunctionCall::new("f", [Expr::from(1), Expr::from(2)]).try_map_args(negate),
+            Ok(FunctionCall::new("f", [Expr::from(-1), Expr::from(-2)]))
+        );
+        assert_eq!(
+            FunctionCall::new("f", [Expr::from(1), Expr::from(false)]).try_map_args(negate),
+            Err("not an integer: Literal(Boolean(false))".to_string())
+        );
+    }
+
+    #[test]
+    fn test_equivalent_floats() {
+        assert_ne!(Expr::from(0.1 + 0.2), Expr::from(0.3));
+        assert!(Expr::from(0.1 + 0.2).equivalent(&Expr::from(0.3)));
+        assert!(Expr::from(f64::NAN).equivalent(&Expr::from(f64::NAN)));
+        assert!(!Expr::from(1.0).equivalent(&Expr::from(1.1)));
+        assert!(Expr::from(1.0).equivalent_within(&Expr::from(1.1), 0.2));
+        assert!(!Expr::from(1.0).equivalent(&Expr::from(1)));
+    }
+
+    #[test]
+    fn test_equivalent_nested_calls() {
+        let a = Expr::call(
+            "f",
+            [Expr::call("g", [Expr::from(0.1 + 0.2)]), Expr::from(1)],
+        );
+        let b = Expr::call("f", [Expr::call("g", [Expr::from(0.3)]), Expr::from(1)]);
+        assert!(a.equivalent(&b));
+
+        let swapped = Expr::call("f", [Expr::from(1), Expr::call("g", [Expr::from(0.3)])]);
+        assert!(!a.equivalent(&swapped));
+        assert!(!a.equivalent(&Expr::call(
+            "h",
+            [Expr::call("g", [Expr::from(0.3)]), Expr::from(1)]
+        )));
+    }
+
+    #[test]
+    fn test_exprs_as_map_keys() {
+        let mut map = HashMap::new();
+        map.insert(Expr::from(42), "integer");
+        map.insert(Expr::from(2.5), "float");
+        map.insert(Expr::call("f", [Expr::from(0.0)]), "call");
+
+        assert_eq!(map.get(&Expr::from(42)), Some(&"integer"));
+        assert_eq!(map.get(&Expr::from(2.5)), Some(&"float"));
+        assert_eq!(map.get(&Expr::call("f", [Expr::from(-0.0)])), Some(&"call"));
+        assert_eq!(map.get(&Expr::from(42.0)), None);
+
+        map.insert(Expr::from(f64::NAN), "nan");
+        assert_eq!(map.get(&Expr::from(f64::NAN)), Some(&"nan"));
+        assert_eq!(map.get(&Expr::from(-f64::NAN)), Some(&"nan"));
+    }
+
+    #[test]
+    fn test_literal_eq_agrees_with_ord_and_hash() {
+        let literals = [
+            Literal::Float(f64::NAN),
+            Literal::Float(-f64::NAN),
+            Literal::Float(0.0),
+            Literal::Float(-0.0),
+            Literal::Float(1.0),
+            Literal::Integer(1),
+            Literal::Boolean(true),
+        ];
+        let hash = |literal: &Literal| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            literal.hash(&mut hasher);
+            hasher.finish()
+        };
+        for a in &literals {
+            for b in &literals {
+                assert_eq!(a == b, a.cmp(b) == Ordering::Equal, "{:?} vs {:?}", a, b);
+                if a == b {
+                    assert_eq!(hash(a), hash(b), "{:?} vs {:?}", a, b);
+                }
+            }
+        }
+
+        let set: std::collections::BTreeSet<_> = literals.iter().cloned().collect();
+        assert_eq!(set.len(), 5);
+        assert!(set.contains(&Literal::Float(f64::NAN)));
+    }
+
+    #[test]
+    fn test_literal_arithmetic() {
+        let int = Literal::Integer;
+        let float = Literal::Float;
+
+        assert_eq!(int(2).checked_add(int(3)), Ok(int(5)));
+        assert_eq!(int(2).checked_sub(float(0.5)), Ok(float(1.5)));
+        assert_eq!(float(1.5).checked_mul(int(2)), Ok(float(3.0)));
+        assert_eq!(int(-7).checked_div(int(2)), Ok(int(-3)));
+        assert_eq!(int(7).checked_div(float(2.0)), Ok(float(3.5)));
+        assert_eq!(int(2).checked_pow(int(10)), Ok(int(1024)));
+        assert_eq!(int(2).checked_pow(int(-1)), Ok(float(0.5)));
+        assert_eq!(float(4.0).checked_pow(float(0.5)), Ok(float(2.0)));
+    }
+
+    #[test]
+    fn test_literal_arithmetic_errors() {
+        let int = Literal::Integer;
+
+        assert_eq!(
+            int(i64::MAX).checked_add(int(1)),
+            Err("integer overflow in 9223372036854775807 + 1".to_string())
+        );
+        assert!(int(i64::MIN).checked_sub(int(1)).is_err());
+        assert!(int(i64::MAX).checked_mul(int(2)).is_err());
+        assert!(int(i64::MIN).checked_div(int(-1)).is_err());
+        assert!(int(10).checked_pow(int(19)).is_err());
+        assert!(int(2).checked_pow(int(i64::MAX)).is_err());
+        assert_eq!(
+            int(1).checked_div(int(0)),
+            Err("division by zero".to_string())
+        );
+        assert_eq!(
+            Literal::Float(1.0).checked_div(Literal::Float(-0.0)),
+            Err("division by zero".to_string())
+        );
+        assert_eq!(
+            int(0).checked_pow(int(-1)),
+            Err("division by zero".to_string())
+        );
+        assert_eq!(
+            Literal::Float(-0.0).checked_pow(Literal::Float(-0.5)),
+            Err("division by zero".to_string())
+        );
+        assert_eq!(
+            Literal::String("a".to_string()).checked_add(int(1)),
+            Err("cannot apply '+' to String and Integer".to_string())
+        );
+    }
+
+    #[test]
+    fn test_literal_ordering() {
+        let mut literals = vec![
+            Literal::Float(f64::NAN),
+            Literal::Integer(3),
+            Literal::Float(-1.0),
+            Literal::Boolean(true),
+            Literal::String("b".to_string()),
+            Literal::Integer(-5),
+            Literal::Boolean(false),
+            Literal::String("a".to_string()),
+            Literal::Float(2.5),
+        ];
+        literals.sort();
+
+        assert_eq!(
+            &literals[..8],
+            &[
+                Literal::String("a".to_string()),
+                Literal::String("b".to_string()),
+                Literal::Boolean(false),
+                Literal::Boolean(true),
+                Literal::Integer(-5),
+                Literal::Integer(3),
+                Literal::Float(-1.0),
+                Literal::Float(2.5),
+            ]
+        );
+        assert!(literals[8].as_float().unwrap().is_nan());
+        assert!(Literal::Integer(100) < Literal::Float(0.5));
+    }
+
+    #[test]
+    fn test_literal_accessors() {
+        let s = Literal::String("x".to_string());
+        assert_eq!(s.as_str(), Some("x"));
+        assert_eq!(s.as_int(), None);
+        assert_eq!(Literal::Integer(1).as_int(), Some(1));
+        assert_eq!(Literal::Integer(1).as_float(), None);
+        assert_eq!(Literal::Float(1.5).as_float(), Some(1.5));
+        assert_eq!(Literal::Boolean(true).as_bool(), Some(true));
+    }
+}
@@ -0,0 +1,16 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpectreError {
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for SpectreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpectreError::Parse { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for SpectreError {}
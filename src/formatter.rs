@@ -0,0 +1,266 @@
+use crate::ast::*;
+use crate::parser::context::{Context, FunctionCallFormat};
+use crate::parser::program::{apply_spec, parse_program};
+
+const MAX_LABEL_LEN: usize = 20;
+
+/// Renders a single expression as a Graphviz DOT graph.
+pub fn to_dot(expr: &Expr) -> String {
+    to_dot_program(std::slice::from_ref(expr))
+}
+
+/// Renders every root expression of a program into one DOT graph. Node ids are
+/// sequential (`n0`, `n1`, ...) so repeated subtrees stay distinct.
+pub fn to_dot_program(nodes: &[Expr]) -> String {
+    let mut output = String::from("digraph {\n    node [shape=box];\n");
+    let mut next_id = 0;
+    let mut stack: Vec<(&Expr, Option<(usize, usize)>)> =
+        nodes.iter().rev().map(|expr| (expr, None)).collect();
+
+    while let Some((expr, parent)) = stack.pop() {
+        let id = next_id;
+        next_id += 1;
+
+        output.push_str(&format!("    n{} [label=\"{}\"];\n", id, node_label(expr)));
+        if let Some((parent_id, index)) = parent {
+            output.push_str(&format!(
+                "    n{} -> n{} [label=\"{}\"];\n",
+                parent_id, id, index
+            ));
+        }
+        if let Expr::FunctionCall(call) = expr {
+            stack.extend(
+                call.args
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(index, arg)| (arg, Some((id, index)))),
+            );
+        }
+    }
+
+    output.push('}');
+    output
+}
+
+/// Renders a program back into source, one statement per line, starting in
+/// `context`'s call format. A header is emitted when that format is not the
+/// default, and each `SPEC` call switches the format of the statements after
+/// it, exactly as when parsing. The output parses back to `nodes` only if every
+/// separator can be displayed (see `FunctionCallFormat::format_call`); use
+/// `format_source` to check that.
+pub fn format_program(nodes: &[Expr], context: &Context) -> String {
+    let mut context = context.clone();
+    let mut output = String::new();
+
+    let format = &context.function_call_format;
+    if *format != FunctionCallFormat::default() {
+        output.push_str(&format!(
+            "{{\"{}\" \"{}\"}}\n",
+            format.pattern(),
+            format.arg_separator()
+        ));
+    }
+    for expr in nodes {
+        output.push_str(&format_expr(expr, &context.function_call_format));
+        output.push('\n');
+        if let Expr::FunctionCall(call) = expr
+            && call.name == "SPEC"
+        {
+            // Invalid directives never come out of the parser; if one is
+            // passed in anyway it is kept, like any other call.
+            let _ = apply_spec(&mut context, call);
+        }
+    }
+    output
+}
+
+/// Parses and reformats a whole program. Fails if the program has parse errors,
+/// since formatting only the statements that parsed would drop the rest, or if
+/// the formatted text would not parse back to the same program.
+pub fn format_source(input: &str) -> Result<String, String> {
+    let result = parse_program(input);
+    let context = result.initial_context.clone();
+    let nodes = result.into_result().map_err(|e| e.to_string())?;
+
+    let formatted = format_program(&nodes, &context);
+    if parse_program(&formatted).into_result().as_ref() != Ok(&nodes) {
+        return Err(
+            "the formatted program would not parse back to the same program; \
+             each argument separator must be literal text, escapes or \\s"
+                .to_string(),
+        );
+    }
+    Ok(formatted)
+}
+
+/// Renders a single expression, with every call in `format`.
+pub fn format_expr(expr: &Expr, format: &FunctionCallFormat) -> String {
+    match expr {
+        Expr::FunctionCall(call) => {
+            let args: Vec<String> = call
+                .args
+                .iter()
+                .map(|arg| format_expr(arg, format))
+                .collect();
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            format.format_call(&call.name, &args)
+        }
+        Expr::Identifier(name) => name.clone(),
+        Expr::Literal(Literal::String(s)) => format!("\"{}\"", s),
+        Expr::Literal(Literal::Integer(i)) => i.to_string(),
+        // `Display` never uses an exponent, but drops the `.0` that tells a
+        // float literal apart from an integer one.
+        Expr::Literal(Literal::Float(f)) if f.fract() == 0.0 && f.is_finite() => {
+            format!("{}.0", f)
+        }
+        Expr::Literal(Literal::Float(f)) => f.to_string(),
+        Expr::Literal(Literal::Boolean(b)) => b.to_string(),
+    }
+}
+
+fn node_label(expr: &Expr) -> String {
+    let (variant, value) = match expr {
+        Expr::FunctionCall(call) => ("FunctionCall", call.name.clone()),
+        Expr::Identifier(name) => ("Identifier", name.clone()),
+        Expr::Literal(Literal::String(s)) => ("String", s.clone()),
+        Expr::Literal(Literal::Integer(i)) => ("Integer", i.to_string()),
+        Expr::Literal(Literal::Float(f)) => ("Float", f.to_string()),
+        Expr::Literal(Literal::Boolean(b)) => ("Boolean", b.to_string()),
+    };
+    format!("{}\\n{}", variant, escape_label(&truncate(&value)))
+}
+
+fn truncate(value: &str) -> String {
+    if value.chars().count() > MAX_LABEL_LEN {
+        let truncated: String = value.chars().take(MAX_LABEL_LEN).collect();
+        format!("{}...", truncated)
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_dot_literal() {
+        let dot = to_dot(&Expr::from(42));
+        assert!(dot.starts_with("digraph {"));
+        assert!(dot.ends_with('}'));
+        assert!(dot.contains("42"));
+    }
+
+    #[test]
+    fn test_to_dot_function_call() {
+        let dot = to_dot(&Expr::call("add", [2.into(), 3.into()]));
+        assert_eq!(
+            dot,
+            "digraph {
+    node [shape=box];
+    n0 [label=\"FunctionCall\\nadd\"];
+    n1 [label=\"Integer\\n2\"];
+    n0 -> n1 [label=\"0\"];
+    n2 [label=\"Integer\\n3\"];
+    n0 -> n2 [label=\"1\"];
+}"
+        );
+    }
+
+    #[test]
+    fn test_to_dot_program_escapes_and_truncates() {
+        let dot = to_dot_program(&[
+            Expr::from("say \"hello\" to everyone".to_string()),
+            Expr::from(1),
+        ]);
+        assert!(dot.contains(r#"n0 [label="String\nsay \"hello\" to every..."];"#));
+        assert!(dot.contains(r#"n1 [label="Integer\n1"];"#));
+        assert!(!dot.contains("->"));
+    }
+
+    #[test]
+    fn test_format_expr() {
+        let lisp = FunctionCallFormat::default();
+        let expr = Expr::call(
+            "f",
+            [
+                Expr::call("g", [Expr::from(1), Expr::from(-2.0)]),
+                Expr::from("a b".to_string()),
+                Expr::Identifier("x".to_string()),
+                Expr::from(0.25),
+                Expr::from(true),
+            ],
+        );
+        assert_eq!(
+            format_expr(&expr, &lisp),
+            r#"(f (g 1 -2.0) "a b" x 0.25 true)"#
+        );
+    }
+
+    #[test]
+    fn test_format_program_round_trip() {
+        let input = r#"{"NAME(ARGS)" ",\s*"}
+            foo(bar(1,2),
+                3.5)
+            SPEC(function_call_format, "NAME[ARGS]", ";")
+            baz[x;"y"]"#;
+        let result = parse_program(input);
+        let formatted = format_program(&result.nodes, &result.initial_context);
+        assert_eq!(
+            formatted,
+            r#"{"NAME(ARGS)" ",\s*"}
+foo(bar(1, 2), 3.5)
+SPEC(function_call_format, "NAME[ARGS]", ";")
+baz[x;"y"]
+"#
+        );
+        assert_eq!(
+            parse_program(&formatted).into_result().unwrap(),
+            result.nodes
+        );
+    }
+
+    #[test]
+    fn test_format_source() {
+        assert_eq!(
+            format_source("  (f 1\n    (g 2))\n").unwrap(),
+            "(f 1 (g 2))\n".to_string()
+        );
+        assert_eq!(
+            format_source("(f 1\n").unwrap_err(),
+            "Unexpected end of input in statement starting on line 1"
+        );
+
+        let infix = "{\"ARG1 NAME ARG2\" \" \"}\n1   plus x\n";
+        assert_eq!(
+            format_source(infix).unwrap(),
+            "{\"ARG1 NAME ARG2\" \" \"}\n1 plus x\n".to_string()
+        );
+    }
+
+    #[test]
+    fn test_format_source_rejects_undisplayable_separator() {
+        // `;|,` is written out verbatim as `f(1;|,2)`, which doesn't parse.
+        let input = "{\"NAME(ARGS)\" \";|,\"}\nf(1,2)\n";
+        let result = parse_program(input);
+        let formatted = format_program(&result.nodes, &result.initial_context);
+        assert!(formatted.contains("f(1;|,2)"));
+
+        assert!(
+            format_source(input)
+                .unwrap_err()
+                .contains("would not parse back")
+        );
+    }
+
+    #[test]
+    fn test_format_program_default_context_has_no_header() {
+        let formatted = format_program(&[Expr::call("f", [1.into()])], &Context::default());
+        assert_eq!(formatted, "(f 1)\n");
+    }
+}
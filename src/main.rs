@@ -1,22 +1,185 @@
 pub mod ast;
 mod error;
+pub mod formatter;
 pub mod parser;
 
+use crossterm::{
+    cursor::MoveTo,
+    execute,
+    terminal::{Clear, ClearType},
+};
+use notify::{EventKind, RecursiveMode, Watcher};
 use std::env;
 use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Editors often emit several events for a single save.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(50);
 
 fn main() -> Result<(), String> {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <file>", args[0]);
+    let mut dot = false;
+    let mut strict = false;
+    let mut watch = false;
+    let mut format = false;
+    let mut format_in_place = false;
+    let mut file_path = None;
+    for arg in &args[1..] {
+        match arg.as_str() {
+            "--dot" => dot = true,
+            "--strict" => strict = true,
+            "--watch" => watch = true,
+            "--format" => format = true,
+            "--format-in-place" => format_in_place = true,
+            flag if flag.starts_with("--") => return Err(format!("Unknown flag: {}", flag)),
+            path => file_path = Some(path),
+        }
+    }
+
+    let Some(file_path) = file_path else {
+        eprintln!(
+            "Usage: {} [--dot] [--strict] [--watch] [--format | --format-in-place] <file>",
+            args[0]
+        );
         return Err("Missing file argument".to_string());
+    };
+
+    if format || format_in_place {
+        if dot || watch {
+            return Err("--format cannot be combined with --dot or --watch".to_string());
+        }
+        return format_file(file_path, format_in_place);
     }
 
-    let file_path = &args[1];
+    if watch {
+        return watch_file(file_path, dot, strict);
+    }
+    run(file_path, dot, strict)
+}
+
+/// Parses the file and prints the result. Parse errors are reported as
+/// warnings and the statements that did parse are still printed, unless
+/// `strict` is set, in which case the first error aborts the run.
+fn run(file_path: &str, dot: bool, strict: bool) -> Result<(), String> {
     let input = fs::read_to_string(file_path).map_err(|e| format!("Failed to read file: {}", e))?;
 
-    let ast = parser::program::parse_program(&input)?;
-    println!("Parsed AST: {:#?}", ast);
+    let result = parser::program::parse_program_with_options(
+        &input,
+        parser::context::Context::default(),
+        parser::program::ParserOptions::default(),
+    );
+    for (_, error) in &result.errors {
+        if strict {
+            return Err(error.to_string());
+        }
+        eprintln!("Warning: {}", error);
+    }
+
+    let ast = result.nodes;
+    if dot {
+        println!("{}", formatter::to_dot_program(&ast));
+    } else {
+        println!("Parsed AST: {:#?}", ast);
+    }
 
     Ok(())
 }
+
+/// Prints the file reformatted in the call format(s) it is written in, or
+/// rewrites it in place. Unlike `run`, any parse error aborts, and so does
+/// output that would not parse back to the same program.
+fn format_file(file_path: &str, in_place: bool) -> Result<(), String> {
+    let input = fs::read_to_string(file_path).map_err(|e| format!("Failed to read file: {}", e))?;
+
+    let formatted = formatter::format_source(&input)
+        .map_err(|e| format!("Cannot format {}: {}", file_path, e))?;
+
+    if in_place {
+        fs::write(file_path, formatted).map_err(|e| format!("Failed to write file: {}", e))
+    } else {
+        print!("{}", formatted);
+        Ok(())
+    }
+}
+
+/// Runs the file, then re-runs it from scratch every time it is modified or
+/// re-created. Ctrl+C exits with the status of the last run.
+fn watch_file(file_path: &str, dot: bool, strict: bool) -> Result<(), String> {
+    let path = fs::canonicalize(file_path).map_err(|e| format!("Failed to read file: {}", e))?;
+    // Watch the parent directory so that deleting and re-creating the file
+    // (as many editors do on save) doesn't end the watch.
+    let directory = path.parent().unwrap_or(Path::new("/"));
+
+    let exit_code = Arc::new(AtomicI32::new(0));
+    let handler_exit_code = Arc::clone(&exit_code);
+    ctrlc::set_handler(move || process::exit(handler_exit_code.load(Ordering::SeqCst)))
+        .map_err(|e| format!("Failed to install Ctrl+C handler: {}", e))?;
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher =
+        notify::recommended_watcher(sender).map_err(|e| format!("Failed to watch file: {}", e))?;
+    watcher
+        .watch(directory, RecursiveMode::NonRecursive)
+        .map_err(|e| format!("Failed to watch file: {}", e))?;
+
+    let run_with_header = |exit_code: &AtomicI32| {
+        println!("=== {} (re-run at {}) ===", file_path, utc_time_of_day());
+        let result = run(file_path, dot, strict);
+        if let Err(e) = &result {
+            eprintln!("Error: {}", e);
+        }
+        exit_code.store(result.map_or(1, |_| 0), Ordering::SeqCst);
+    };
+
+    run_with_header(&exit_code);
+    while let Ok(event) = receiver.recv() {
+        let mut events = vec![event];
+        while let Ok(event) = receiver.recv_timeout(WATCH_DEBOUNCE) {
+            events.push(event);
+        }
+
+        let mut relevant = false;
+        let mut changed = false;
+        for event in events {
+            let event = event.map_err(|e| format!("Failed to watch file: {}", e))?;
+            if event.paths.iter().any(|p| p == &path) {
+                relevant = true;
+                changed |= matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_));
+            }
+        }
+        if !relevant {
+            continue;
+        }
+
+        if !path.exists() {
+            eprintln!(
+                "Warning: {} was deleted, waiting for it to reappear",
+                file_path
+            );
+        } else if changed {
+            let _ = execute!(io::stdout(), Clear(ClearType::All), MoveTo(0, 0));
+            run_with_header(&exit_code);
+        }
+    }
+
+    Ok(())
+}
+
+fn utc_time_of_day() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+        % 86_400;
+    format!(
+        "{:02}:{:02}:{:02} UTC",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
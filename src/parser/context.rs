@@ -1,21 +1,58 @@
-use crate::ast::FunctionCall;
-use nom::{Parser, error::Error as NomError};
-use regex::Regex;
+use crate::parser::program::parse_header;
+use nom::{Parser, combinator::all_consuming};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
-#[derive(Debug, Clone, PartialEq)]
+// Non-ASCII math and other symbols (`∑`, `∏`, emoji), which identifiers accept
+// alongside XID characters. ASCII operators such as `+` stay punctuation.
+pub(crate) const SYMBOL_CLASS: &str = r"[\p{Sm}\p{So}]--\p{ASCII}";
+
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"NAME|ARGS|ARG([1-9][0-9]*)").unwrap());
+static WHITESPACE_CLASS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\\s[*+?]?").unwrap());
+static ESCAPE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\\(.)").unwrap());
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Context {
     pub function_call_format: FunctionCallFormat,
+    #[serde(default)]
+    pub line_ending: LineEnding,
     // pub function_def_format: FunctionDefFormat,
     // pub if_else_format: IfElseFormat,
     // pub string_format: StringFormat,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "FunctionCallFormatFields")]
 pub struct FunctionCallFormat {
     pattern: String,       // e.g. "NAME(ARGS)"
     arg_separator: String, // e.g. ","
 }
 
+// Deserialized formats go through `FunctionCallFormat::try_new`, so a context
+// file cannot load a format that would fail every statement.
+#[derive(Deserialize)]
+struct FunctionCallFormatFields {
+    pattern: String,
+    arg_separator: String,
+}
+
+impl TryFrom<FunctionCallFormatFields> for FunctionCallFormat {
+    type Error = String;
+
+    fn try_from(fields: FunctionCallFormatFields) -> Result<Self, Self::Error> {
+        FunctionCallFormat::try_new(fields.pattern, fields.arg_separator)
+    }
+}
+
 impl FunctionCallFormat {
     pub fn new(pattern: String, arg_separator: String) -> Self {
         Self {
@@ -24,6 +61,19 @@ impl FunctionCallFormat {
         }
     }
 
+    /// Like `new`, but rejects formats that could never parse a call: the
+    /// pattern needs exactly one `NAME` and one `ARGS` placeholder, and the
+    /// separator must be a non-empty regular expression.
+    pub fn try_new(pattern: String, arg_separator: String) -> Result<Self, String> {
+        validate_pattern(&pattern)?;
+        if arg_separator.is_empty() {
+            return Err("separator must not be empty".to_string());
+        }
+        Regex::new(&arg_separator)
+            .map_err(|e| format!("separator is not a valid regular expression: {}", e))?;
+        Ok(Self::new(pattern, arg_separator))
+    }
+
     pub fn pattern(&self) -> &String {
         &self.pattern
     }
@@ -31,14 +81,86 @@ impl FunctionCallFormat {
     pub fn arg_separator(&self) -> &String {
         &self.arg_separator
     }
+
+    /// The inverse of parsing: renders a call in this format. `NAME` becomes
+    /// `name`, `ARGS` becomes the joined arguments, and positional `ARG1`,
+    /// `ARG2`, ... placeholders (as in an infix `ARG1 NAME ARG2` pattern) are
+    /// substituted individually.
+    ///
+    /// Arguments are joined with the separator as displayed by
+    /// `display_separator`, which only understands literal text, escaped
+    /// characters and `\s`. A separator using other regex syntax, such as
+    /// `;|,` or `[,;]`, is written out verbatim, and the result will not parse
+    /// back in this format.
+    pub fn format_call(&self, name: &str, args: &[&str]) -> String {
+        PLACEHOLDER
+            .replace_all(&self.pattern, |captures: &Captures| match &captures[0] {
+                "NAME" => name.to_string(),
+                "ARGS" => args.join(&self.display_separator()),
+                _ => match captures[1].parse::<usize>() {
+                    Ok(index) => args.get(index - 1).copied().unwrap_or_default().to_string(),
+                    // Only reachable for unvalidated patterns (see `new`): an
+                    // index too large to be an argument is kept as text.
+                    Err(_) => captures[0].to_string(),
+                },
+            })
+            .into_owned()
+    }
+
+    pub fn example_call(&self) -> String {
+        self.format_call("foo", &["arg1", "arg2"])
+    }
+
+    // The separator is a regex; render whitespace classes as a single space and
+    // drop escapes, so `,\s*` is displayed as `, `.
+    fn display_separator(&self) -> String {
+        let separator = WHITESPACE_CLASS.replace_all(&self.arg_separator, " ");
+        ESCAPE.replace_all(&separator, "$1").into_owned()
+    }
+}
+
+impl fmt::Display for FunctionCallFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.example_call())
+    }
+}
+
+/// How a program's lines are terminated. `Auto` accepts any mix of `\n`,
+/// `\r\n` and `\r`; the other modes accept only their own line ending.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    Cr,
+    #[default]
+    Auto,
+}
+
+impl LineEnding {
+    /// Splits `input` into lines at this line ending. Any other `\r` or `\n`
+    /// stays part of a line.
+    pub fn lines<'a>(&self, input: &'a str) -> Vec<&'a str> {
+        match self {
+            LineEnding::Lf => input.split('\n').collect(),
+            LineEnding::Crlf => input.split("\r\n").collect(),
+            LineEnding::Cr => input.split('\r').collect(),
+            LineEnding::Auto => input
+                .split('\n')
+                .flat_map(|line| line.strip_suffix('\r').unwrap_or(line).split('\r'))
+                .collect(),
+        }
+    }
+}
+
+impl Default for FunctionCallFormat {
+    fn default() -> Self {
+        FunctionCallFormat::new("(NAME ARGS)".to_string(), " ".to_string())
+    }
 }
 
 impl Default for Context {
     fn default() -> Self {
-        Context::new(FunctionCallFormat::new(
-            "(NAME ARGS)".to_string(),
-            " ".to_string(),
-        ))
+        Context::new(FunctionCallFormat::default())
     }
 }
 
@@ -46,35 +168,79 @@ impl Context {
     pub fn new(function_call_format: FunctionCallFormat) -> Self {
         Context {
             function_call_format,
+            line_ending: LineEnding::default(),
         }
     }
 
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    pub fn from_json(s: &str) -> Result<Context, serde_json::Error> {
+        serde_json::from_str(s)
+    }
+
+    /// Builds a context from a program header such as `{"NAME(ARGS)" ","}`
+    /// without parsing the rest of the program.
+    pub fn from_program_header(header: &str) -> Result<Context, String> {
+        let (_, (pattern, separator)) = all_consuming(parse_header)
+            .parse(header.trim())
+            .map_err(|_| "expected a header of the form {\"PATTERN\" \"SEPARATOR\"}".to_string())?;
+        let format = FunctionCallFormat::try_new(pattern.to_string(), separator.to_string())?;
+        Ok(Context::new(format))
+    }
+
     pub fn generate_function_call_regex(&self) -> Result<Regex, String> {
         let pattern = self.function_call_format.pattern.clone();
-        let arg_separator = self.function_call_format.arg_separator.clone();
-        let name_count = pattern.matches("NAME").count();
-        let args_count = pattern.matches("ARGS").count();
+        validate_pattern(&pattern)?;
 
-        if name_count != 1 {
-            return Err(format!(
-                "Pattern must contain exactly one 'NAME', found {}",
-                name_count
-            ));
-        }
+        let escaped = regex::escape(&pattern);
+        let regex_string =
+            PLACEHOLDER.replace_all(&escaped, |captures: &Captures| match &captures[0] {
+                "NAME" => format!("(?P<name>{})", identifier_regex()),
+                "ARGS" => r"(?P<args>.*?)".to_string(),
+                _ => format!(r"(?P<arg{}>.*?)", &captures[1]),
+            });
 
-        if args_count != 1 {
-            return Err(format!(
-                "Pattern must contain exactly one 'ARGS', found {}",
-                args_count
-            ));
+        Regex::new(&format!("^{}$", regex_string)).map_err(|e| format!("Invalid regex: {}", e))
+    }
+
+    /// Matches the opening of a function call at the start of a line: the
+    /// pattern's text up to and including NAME, and any text between NAME and
+    /// the first argument. `None` when that opening is not distinctive, as for
+    /// `NAME ARGS` or infix patterns, where any line could start a call.
+    pub fn generate_call_start_regex(&self) -> Option<Regex> {
+        let pattern = &self.function_call_format.pattern;
+        validate_pattern(pattern).ok()?;
+
+        let name = PLACEHOLDER
+            .find_iter(pattern)
+            .find(|m| m.as_str() == "NAME")?;
+        let prefix = &pattern[..name.start()];
+        if PLACEHOLDER.is_match(prefix) {
+            return None;
+        }
+        let rest = &pattern[name.end()..];
+        let between = PLACEHOLDER
+            .find(rest)
+            .map_or(rest, |m| &rest[..m.start()])
+            .trim_end();
+        if prefix.trim().is_empty() && between.is_empty() {
+            return None;
         }
 
-        let escaped_separator = regex::escape(&arg_separator);
-        let regex_string = pattern
-            .replace("NAME", r"([a-zA-Z_][a-zA-Z0-9_]*)")
-            .replace("ARGS", r"(.*?)");
+        Regex::new(&format!(
+            "^{}{}{}",
+            regex::escape(prefix),
+            identifier_regex(),
+            regex::escape(between)
+        ))
+        .ok()
+    }
 
-        Regex::new(&regex_string).map_err(|e| format!("Invalid regex: {}", e))
+    pub fn generate_arg_separator_regex(&self) -> Result<Regex, String> {
+        Regex::new(&self.function_call_format.arg_separator)
+            .map_err(|e| format!("Invalid argument separator: {}", e))
     }
 
     pub fn update_function_call_format(
@@ -82,7 +248,407 @@ impl Context {
         pattern: String,
         arg_separator: String,
     ) -> Result<(), String> {
-        self.function_call_format = FunctionCallFormat::new(pattern, arg_separator);
+        self.function_call_format = FunctionCallFormat::try_new(pattern, arg_separator)?;
         Ok(())
     }
+
+    /// Loads a context from the JSON file at `path` and keeps it up to date:
+    /// a background thread checks the file every `poll_interval` and swaps in
+    /// the new context whenever it changes. If the new contents fail to
+    /// deserialize, the error is logged and the old context is kept.
+    ///
+    /// The thread runs until `ContextWatcher::stop_watching` is called or the
+    /// returned watcher is dropped.
+    pub fn watch_file(path: &Path, poll_interval: Duration) -> io::Result<ContextWatcher> {
+        let context = Context::from_json(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let watcher = ContextWatcher {
+            context: Arc::new(RwLock::new(context)),
+            stop: Arc::new(AtomicBool::new(false)),
+        };
+
+        let path = path.to_path_buf();
+        let shared = Arc::downgrade(&watcher.context);
+        let stop = Arc::clone(&watcher.stop);
+        let mut last_seen = file_stamp(&path);
+        thread::spawn(move || {
+            loop {
+                thread::sleep(poll_interval);
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Some(context) = shared.upgrade() else {
+                    break;
+                };
+                let stamp = file_stamp(&path);
+                if stamp == last_seen {
+                    continue;
+                }
+                last_seen = stamp;
+                reload(&path, &context);
+            }
+        });
+
+        Ok(watcher)
+    }
+}
+
+/// A context kept in sync with a file by `Context::watch_file`. Dropping the
+/// watcher stops the background thread; clones of `context()` keep their
+/// last value.
+#[derive(Debug)]
+pub struct ContextWatcher {
+    context: Arc<RwLock<Context>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl ContextWatcher {
+    pub fn context(&self) -> &Arc<RwLock<Context>> {
+        &self.context
+    }
+
+    /// Stops the background thread. The context keeps its current value.
+    pub fn stop_watching(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for ContextWatcher {
+    fn drop(&mut self) {
+        self.stop_watching();
+    }
+}
+
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+fn reload(path: &Path, context: &RwLock<Context>) {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) => {
+            eprintln!("Failed to read context file {}: {}", path.display(), e);
+            return;
+        }
+    };
+    match Context::from_json(&json) {
+        Ok(new_context) => *context.write().unwrap() = new_context,
+        Err(e) => eprintln!(
+            "Failed to reload context from {}, keeping the previous one: {}",
+            path.display(),
+            e
+        ),
+    }
+}
+
+// The NAME placeholder's regex, matching what `parse_identifier` accepts.
+fn identifier_regex() -> String {
+    format!(
+        r"[\p{{XID_Start}}_[{0}]][\p{{XID_Continue}}[{0}]]*",
+        SYMBOL_CLASS
+    )
+}
+
+// A pattern has one `NAME` and either one `ARGS` or positional `ARG1` to
+// `ARGn` placeholders, each exactly once.
+fn validate_pattern(pattern: &str) -> Result<(), String> {
+    let placeholders: Vec<&str> = PLACEHOLDER.find_iter(pattern).map(|m| m.as_str()).collect();
+    let count = |placeholder: &str| placeholders.iter().filter(|p| **p == placeholder).count();
+    let mut positional: Vec<&str> = placeholders
+        .iter()
+        .copied()
+        .filter(|p| *p != "NAME" && *p != "ARGS")
+        .collect();
+
+    let mut required = vec!["NAME"];
+    if positional.is_empty() {
+        required.push("ARGS");
+    }
+    for placeholder in required {
+        if count(placeholder) != 1 {
+            return Err(format!(
+                "pattern must contain exactly one {} placeholder, found {}",
+                placeholder,
+                count(placeholder)
+            ));
+        }
+    }
+    if positional.is_empty() {
+        return Ok(());
+    }
+
+    if count("ARGS") > 0 {
+        return Err("pattern cannot mix ARGS with ARG1, ARG2, ... placeholders".to_string());
+    }
+    let mut expected: Vec<String> = (1..=positional.len())
+        .map(|index| format!("ARG{}", index))
+        .collect();
+    positional.sort_unstable();
+    expected.sort_unstable();
+    if positional != expected {
+        return Err(format!(
+            "positional placeholders must be ARG1 to ARG{}, each exactly once",
+            positional.len()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_json_round_trip() {
+        let context = Context::default();
+        let json = context.to_json().unwrap();
+        assert_eq!(
+            json,
+            r#"{"function_call_format":{"pattern":"(NAME ARGS)","arg_separator":" "},"line_ending":"Auto"}"#
+        );
+        assert_eq!(Context::from_json(&json).unwrap(), context);
+        assert!(Context::from_json("{}").is_err());
+        let error = Context::from_json(
+            r#"{"function_call_format":{"pattern":"NAME","arg_separator":"("}}"#,
+        )
+        .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("pattern must contain exactly one ARGS placeholder")
+        );
+
+        // Contexts saved before line endings were configurable still load.
+        let json = r#"{"function_call_format":{"pattern":"(NAME ARGS)","arg_separator":" "}}"#;
+        assert_eq!(Context::from_json(json).unwrap(), context);
+    }
+
+    #[test]
+    fn test_from_program_header() {
+        let context = Context::from_program_header(r#"{"NAME[ARGS]" ";"}"#).unwrap();
+        assert_eq!(
+            context.function_call_format,
+            FunctionCallFormat::new("NAME[ARGS]".to_string(), ";".to_string())
+        );
+
+        assert!(Context::from_program_header(r#"{"NAME[ARGS]"}"#).is_err());
+        assert_eq!(
+            Context::from_program_header(r#"{"NAME" ";"}"#),
+            Err("pattern must contain exactly one ARGS placeholder, found 0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_format_call() {
+        let c_style = FunctionCallFormat::new("NAME(ARGS)".to_string(), ", ".to_string());
+        let lisp = FunctionCallFormat::default();
+
+        assert_eq!(c_style.format_call("f", &["x", "y"]), "f(x, y)");
+        assert_eq!(lisp.format_call("f", &["x", "y"]), "(f x y)");
+        assert_eq!(c_style.format_call("f", &[]), "f()");
+    }
+
+    #[test]
+    fn test_format_call_with_regex_separator() {
+        let format = FunctionCallFormat::new("NAME(ARGS)".to_string(), r",\s*".to_string());
+        assert_eq!(format.format_call("f", &["x", "y"]), "f(x, y)");
+
+        let format = FunctionCallFormat::new("NAME[ARGS]".to_string(), r"\|".to_string());
+        assert_eq!(format.format_call("f", &["x", "y"]), "f[x|y]");
+    }
+
+    #[test]
+    fn test_format_infix_call() {
+        let infix = FunctionCallFormat::new("ARG1 NAME ARG2".to_string(), " ".to_string());
+        assert_eq!(infix.format_call("plus", &["1", "2"]), "1 plus 2");
+
+        let unvalidated = FunctionCallFormat::new(
+            "NAME(ARGS)ARG99999999999999999999999".to_string(),
+            ",".to_string(),
+        );
+        assert_eq!(
+            unvalidated.format_call("f", &["1"]),
+            "f(1)ARG99999999999999999999999"
+        );
+    }
+
+    #[test]
+    fn test_generate_call_start_regex() {
+        let call_start = |pattern: &str| {
+            Context::new(FunctionCallFormat::new(
+                pattern.to_string(),
+                " ".to_string(),
+            ))
+            .generate_call_start_regex()
+            .map(|regex| regex.as_str().to_string())
+        };
+        let identifier = identifier_regex();
+
+        assert_eq!(
+            call_start("(NAME ARGS)"),
+            Some(format!(r"^\({}", identifier))
+        );
+        assert_eq!(
+            call_start("NAME(ARGS)"),
+            Some(format!(r"^{}\(", identifier))
+        );
+        assert_eq!(call_start("NAME: ARGS"), Some(format!("^{}:", identifier)));
+        assert_eq!(call_start("NAME ARGS"), None);
+        assert_eq!(call_start("ARG1 NAME ARG2"), None);
+        assert_eq!(call_start("NAME"), None);
+    }
+
+    #[test]
+    fn test_positional_placeholders() {
+        let try_new =
+            |pattern: &str| FunctionCallFormat::try_new(pattern.to_string(), " ".to_string());
+
+        assert!(try_new("ARG1 NAME ARG2").is_ok());
+        assert!(try_new("NAME ARG2 ARG1").is_ok());
+        assert_eq!(
+            try_new("ARG1 NAME ARG3"),
+            Err("positional placeholders must be ARG1 to ARG2, each exactly once".to_string())
+        );
+        assert_eq!(
+            try_new("ARG1 NAME ARG1"),
+            Err("positional placeholders must be ARG1 to ARG2, each exactly once".to_string())
+        );
+        assert!(try_new("NAME ARG99999999999999999999999").is_err());
+        assert_eq!(
+            try_new("NAME(ARGS)ARG1"),
+            Err("pattern cannot mix ARGS with ARG1, ARG2, ... placeholders".to_string())
+        );
+        assert_eq!(
+            try_new("ARG1 ARG2"),
+            Err("pattern must contain exactly one NAME placeholder, found 0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_example_call() {
+        let c_style = FunctionCallFormat::new("NAME(ARGS)".to_string(), ",".to_string());
+        assert_eq!(c_style.example_call(), "foo(arg1,arg2)");
+        assert_eq!(FunctionCallFormat::default().to_string(), "(foo arg1 arg2)");
+        assert_eq!(
+            format!("Expected call in format: {}", c_style),
+            "Expected call in format: foo(arg1,arg2)"
+        );
+    }
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    fn temp_context_file(name: &str, context: &Context) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "spectre-context-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, context.to_json().unwrap()).unwrap();
+        path
+    }
+
+    // Serializes to a different length than the default context, so a rewrite
+    // is noticed even when modification times have a coarse resolution.
+    fn c_style() -> Context {
+        Context::new(FunctionCallFormat::new(
+            "NAME(ARGS)".to_string(),
+            ",".to_string(),
+        ))
+    }
+
+    fn wait_for(context: &Arc<RwLock<Context>>, expected: &Context) -> bool {
+        for _ in 0..200 {
+            if *context.read().unwrap() == *expected {
+                return true;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        false
+    }
+
+    #[test]
+    fn test_watch_file_reloads_context() {
+        let path = temp_context_file("reload", &Context::default());
+        let watcher = Context::watch_file(&path, POLL_INTERVAL).unwrap();
+        let context = watcher.context();
+        assert_eq!(*context.read().unwrap(), Context::default());
+
+        fs::write(&path, c_style().to_json().unwrap()).unwrap();
+        assert!(wait_for(context, &c_style()));
+
+        watcher.stop_watching();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_watch_file_keeps_context_on_invalid_json() {
+        let path = temp_context_file("invalid", &Context::default());
+        let watcher = Context::watch_file(&path, POLL_INTERVAL).unwrap();
+        let context = watcher.context();
+
+        fs::write(&path, "{not json").unwrap();
+        thread::sleep(POLL_INTERVAL * 10);
+        assert_eq!(*context.read().unwrap(), Context::default());
+
+        // Well-formed JSON with a format that fails validation is kept out too.
+        fs::write(
+            &path,
+            r#"{"function_call_format":{"pattern":"NAME","arg_separator":"("}}"#,
+        )
+        .unwrap();
+        thread::sleep(POLL_INTERVAL * 10);
+        assert_eq!(*context.read().unwrap(), Context::default());
+
+        // The watcher is still running after a failed reload.
+        fs::write(&path, c_style().to_json().unwrap()).unwrap();
+        assert!(wait_for(context, &c_style()));
+
+        watcher.stop_watching();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_stop_watching() {
+        let path = temp_context_file("stop", &Context::default());
+        let watcher = Context::watch_file(&path, POLL_INTERVAL).unwrap();
+
+        watcher.stop_watching();
+        thread::sleep(POLL_INTERVAL * 2);
+        fs::write(&path, c_style().to_json().unwrap()).unwrap();
+        thread::sleep(POLL_INTERVAL * 10);
+        assert_eq!(*watcher.context().read().unwrap(), Context::default());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_dropping_watcher_stops_watching() {
+        let path = temp_context_file("drop", &Context::default());
+        let watcher = Context::watch_file(&path, POLL_INTERVAL).unwrap();
+        let context = Arc::clone(watcher.context());
+
+        drop(watcher);
+        thread::sleep(POLL_INTERVAL * 2);
+        fs::write(&path, c_style().to_json().unwrap()).unwrap();
+        thread::sleep(POLL_INTERVAL * 10);
+        assert_eq!(*context.read().unwrap(), Context::default());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_watch_file_rejects_invalid_initial_context() {
+        let path = std::env::temp_dir().join(format!(
+            "spectre-context-initial-{}.json",
+            std::process::id()
+        ));
+        fs::write(&path, "{}").unwrap();
+        let error = Context::watch_file(&path, POLL_INTERVAL).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+
+        assert!(Context::watch_file(&path, POLL_INTERVAL).is_err());
+    }
 }
@@ -1,21 +1,41 @@
 use crate::ast::*;
+use crate::error::SpectreError;
 use crate::parser::context::*;
 use nom::{
     IResult, Parser,
     branch::alt,
     bytes::complete::{tag, take_while, take_while1},
-    character::complete::{multispace0, multispace1},
-    combinator::{map, opt, recognize},
-    error::Error,
-    multi::separated_list0,
-    sequence::{delimited, pair, preceded, terminated},
+    character::complete::{multispace0, multispace1, satisfy},
+    combinator::{all_consuming, map, map_res, not, opt, recognize},
+    sequence::{delimited, pair, separated_pair, terminated},
 };
 use regex::Regex;
+use std::cell::Cell;
+use std::sync::LazyLock;
+use unicode_ident::{is_xid_continue, is_xid_start};
 
+static SYMBOL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!("^[{}]$", SYMBOL_CLASS)).unwrap());
+
+fn is_symbol(c: char) -> bool {
+    !c.is_ascii() && SYMBOL.is_match(c.encode_utf8(&mut [0; 4]))
+}
+
+fn is_identifier_start(c: char) -> bool {
+    is_xid_start(c) || c == '_' || is_symbol(c)
+}
+
+fn is_identifier_continue(c: char) -> bool {
+    is_xid_continue(c) || is_symbol(c)
+}
+
+// Identifiers follow Unicode Standard Annex #31 (XID_Start / XID_Continue),
+// with `_` additionally allowed as a first character and non-ASCII symbols
+// (`∑`, `∏`, emoji) allowed anywhere. Keep in sync with the NAME regex.
 fn parse_identifier(input: &str) -> IResult<&str, &str> {
     recognize(pair(
-        take_while1(|c: char| c.is_alphabetic() || c == '_'),
-        take_while(|c: char| c.is_alphanumeric() || c == '_'),
+        take_while1(is_identifier_start),
+        take_while(is_identifier_continue),
     ))
     .parse(input)
 }
@@ -26,86 +46,485 @@ fn parse_literal(input: &str) -> IResult<&str, Literal> {
             delimited(tag("\""), take_while(|c: char| c != '"'), tag("\"")),
             |s: &str| Literal::String(s.to_string()),
         ),
-        // Boolean literal
-        map(tag("true"), |_| Literal::Boolean(true)),
-        map(tag("false"), |_| Literal::Boolean(false)),
-        // Integer literal (simplified)
-        map(take_while1(|c: char| c.is_ascii_digit()), |s: &str| {
-            Literal::Integer(s.parse().unwrap())
-        }),
+        // Boolean literal, but not the start of an identifier like `true_value`
+        map(
+            terminated(tag("true"), not(satisfy(is_identifier_continue))),
+            |_| Literal::Boolean(true),
+        ),
+        map(
+            terminated(tag("false"), not(satisfy(is_identifier_continue))),
+            |_| Literal::Boolean(false),
+        ),
+        // Float literal: `d+.d*` or `.d+`, optionally negative
+        map_res(
+            recognize(pair(
+                opt(tag("-")),
+                alt((
+                    recognize((
+                        take_while1(|c: char| c.is_ascii_digit()),
+                        tag("."),
+                        take_while(|c: char| c.is_ascii_digit()),
+                    )),
+                    recognize(pair(tag("."), take_while1(|c: char| c.is_ascii_digit()))),
+                )),
+            )),
+            |s: &str| s.parse().map(Literal::Float),
+        ),
+        // Integer literal, optionally negative
+        map_res(
+            recognize(pair(
+                opt(tag("-")),
+                take_while1(|c: char| c.is_ascii_digit()),
+            )),
+            |s: &str| s.parse().map(Literal::Integer),
+        ),
     ))
     .parse(input)
 }
 
-fn parse_function_call<'a>(context: &'a Context, input: &'a str) -> IResult<&'a str, FunctionCall> {
-    let (input, _) = multispace0(input)?;
+/// Limits on the size of each parsed statement. `max_depth` is counted like
+/// `Expr::depth` and `max_nodes` like `Expr::node_count`. Parsing recurses once
+/// per nesting level, so `max_depth` keeps pathological input from overflowing
+/// the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserOptions {
+    pub max_depth: usize,
+    pub max_nodes: usize,
+}
+
+impl Default for ParserOptions {
+    // The default depth leaves headroom on a 2 MB thread stack in debug builds.
+    fn default() -> Self {
+        ParserOptions {
+            max_depth: 128,
+            max_nodes: 100_000,
+        }
+    }
+}
+
+/// Parses expressions in one context's call format. The format's regexes are
+/// compiled once here, not again for every expression and argument.
+struct ExpressionParser {
+    // `None` when the format does not compile, in which case only literals and
+    // identifiers parse.
+    call_syntax: Option<(Regex, Regex)>,
+    // Recognizes a line that opens a new call; see `starts_call`.
+    call_start: Option<Regex>,
+    options: ParserOptions,
+    // Expressions parsed so far in the current statement.
+    nodes: Cell<usize>,
+}
+
+impl ExpressionParser {
+    fn new(context: &Context, options: ParserOptions) -> Self {
+        let call_syntax = context
+            .generate_function_call_regex()
+            .ok()
+            .zip(context.generate_arg_separator_regex().ok());
+        ExpressionParser {
+            call_syntax,
+            call_start: context.generate_call_start_regex(),
+            options,
+            nodes: Cell::new(0),
+        }
+    }
+
+    /// Whether `line`, untrimmed, opens a call at column 0. Used to resync
+    /// after a statement that was never closed, so a nested call written at
+    /// column 0 inside an unclosed statement starts a new statement.
+    fn starts_call(&self, line: &str) -> bool {
+        self.call_start
+            .as_ref()
+            .is_some_and(|regex| regex.is_match(line))
+    }
+
+    /// Parses one whole statement. Exceeding a limit is a `Failure` with
+    /// `ErrorKind::TooLarge` for the depth and `ErrorKind::Count` for the
+    /// node count.
+    fn statement<'a>(&self, input: &'a str) -> IResult<&'a str, Expr> {
+        self.nodes.set(0);
+        all_consuming(|input| self.expression(input, 1)).parse(input)
+    }
 
-    let regex = context.generate_function_call_regex().map_err(|_| {
-        nom::Err::Error(nom::error::Error::new(
-            input,
-            nom::error::ErrorKind::RegexpMatch,
+    // `depth` is the depth of the expression being parsed, 1 at the top level.
+    fn expression<'a>(&self, input: &'a str, depth: usize) -> IResult<&'a str, Expr> {
+        let (input, _) = multispace0(input)?;
+        if depth > self.options.max_depth {
+            return Err(nom::Err::Failure(nom::error::Error::new(
+                input,
+                nom::error::ErrorKind::TooLarge,
+            )));
+        }
+        self.nodes.set(self.nodes.get() + 1);
+        if self.nodes.get() > self.options.max_nodes {
+            return Err(nom::Err::Failure(nom::error::Error::new(
+                input,
+                nom::error::ErrorKind::Count,
+            )));
+        }
+
+        alt((
+            map(|input| self.function_call(input, depth), Expr::FunctionCall),
+            map(parse_literal, Expr::Literal),
+            map(parse_identifier, |s: &str| Expr::Identifier(s.to_string())),
         ))
-    })?;
+        .parse(input)
+    }
 
-    let end_pos = input
-        .find(|c: char| ['\n', '\r'].contains(&c))
-        .unwrap_or(input.len());
-    let line = &input[..end_pos];
+    fn function_call<'a>(&self, input: &'a str, depth: usize) -> IResult<&'a str, FunctionCall> {
+        let (input, _) = multispace0(input)?;
 
-    if let Some(captures) = regex.captures(line.trim()) {
-        let name = captures.get(1).unwrap().as_str().to_string();
-        let args_str = captures.get(2).unwrap().as_str();
+        let Some((regex, separator_pattern)) = &self.call_syntax else {
+            return Err(nom::Err::Error(nom::error::Error::new(
+                input,
+                nom::error::ErrorKind::RegexpMatch,
+            )));
+        };
 
-        let mut failed = false;
-        let args = if args_str.trim().is_empty() {
-            Vec::new()
-        } else {
-            args_str
-                .split(context.function_call_format.arg_separator())
+        let end_pos = input
+            .find(|c: char| ['\n', '\r'].contains(&c))
+            .unwrap_or(input.len());
+        let line = &input[..end_pos];
+
+        if let Some(captures) = regex.captures(line.trim()) {
+            let name = captures["name"].to_string();
+            let arg_strs = match captures.name("args") {
+                Some(args) if args.as_str().trim().is_empty() => Vec::new(),
+                Some(args) => extract_args(args.as_str(), separator_pattern),
+                // Positional `ARG1`, `ARG2`, ... placeholders.
+                None => (1..)
+                    .map_while(|index| captures.name(&format!("arg{}", index)))
+                    .map(|arg| arg.as_str())
+                    .collect(),
+            };
+
+            let args = arg_strs
+                .into_iter()
                 .map(|arg| {
-                    let trimmed = arg.trim();
-                    if let Ok((_, literal)) = parse_literal(trimmed) {
-                        Expr::Literal(literal)
-                    } else {
-                        failed = true;
-                        Expr::Literal(Literal::String(trimmed.to_string()))
-                    }
+                    all_consuming(|arg| self.expression(arg, depth + 1))
+                        .parse(arg.trim())
+                        .map(|(_, expr)| expr)
                 })
-                .collect()
-        };
-        if failed {
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| match e {
+                    // Over a limit: give up instead of trying other branches.
+                    nom::Err::Failure(e) => nom::Err::Failure(e),
+                    _ => {
+                        nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Fail))
+                    }
+                })?;
+            Ok((&input[end_pos..], FunctionCall { name, args }))
+        } else {
             Err(nom::Err::Error(nom::error::Error::new(
                 input,
-                nom::error::ErrorKind::Fail,
+                nom::error::ErrorKind::RegexpMatch,
             )))
+        }
+    }
+}
+
+/// Splits `input` on matches of `separator_pattern`, ignoring separators that
+/// occur inside brackets or string literals, so nested calls such as
+/// `foo(bar(1, 2), 3)` keep their own arguments together.
+fn extract_args<'a>(input: &'a str, separator_pattern: &Regex) -> Vec<&'a str> {
+    let mut args = Vec::new();
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut start = 0;
+    let mut pos = 0;
+
+    while let Some(c) = input[pos..].chars().next() {
+        if in_string {
+            if c == '"' {
+                in_string = false;
+            }
         } else {
-            Ok((&input[end_pos..], FunctionCall { name, args }))
+            match c {
+                '"' => in_string = true,
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                _ if depth == 0 => {
+                    if let Some(m) = separator_pattern
+                        .find_at(input, pos)
+                        .filter(|m| m.start() == pos && !m.is_empty())
+                    {
+                        args.push(&input[start..pos]);
+                        pos = m.end();
+                        start = pos;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
         }
-    } else {
-        Err(nom::Err::Error(nom::error::Error::new(
-            input,
-            nom::error::ErrorKind::RegexpMatch,
-        )))
+        pos += c.len_utf8();
     }
+    args.push(&input[start..]);
+    args
 }
 
 pub fn parse_expression<'a>(context: &'a Context, input: &'a str) -> IResult<&'a str, Expr> {
-    // input here is
-    todo!()
+    ExpressionParser::new(context, ParserOptions::default()).expression(input, 1)
 }
 
-pub fn parse_program(input: &str) -> Result<Vec<Expr>, String> {
+fn parse_string(input: &str) -> IResult<&str, &str> {
+    delimited(tag("\""), take_while(|c: char| c != '"'), tag("\"")).parse(input)
+}
+
+/// Parses the optional program header `{"PATTERN" "SEPARATOR"}` which sets the
+/// initial function call format.
+pub(crate) fn parse_header(input: &str) -> IResult<&str, (&str, &str)> {
+    delimited(
+        pair(tag("{"), multispace0),
+        separated_pair(parse_string, multispace1, parse_string),
+        pair(multispace0, tag("}")),
+    )
+    .parse(input)
+}
+
+/// A statement accumulated from one or more lines. Bracket depth and string
+/// state are updated as each line is added, so every line is scanned once.
+#[derive(Debug, Default)]
+struct PendingStatement {
+    text: String,
+    depth: i64,
+    in_string: bool,
+}
+
+impl PendingStatement {
+    fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Appends a trimmed line, joined with a space unless the join is right
+    /// inside a bracket: `(f 1` followed by `)` becomes `(f 1)`, since a space
+    /// before the `)` would read as an empty last argument.
+    fn push_line(&mut self, line: &str) {
+        let inside_bracket =
+            self.text.ends_with(['(', '[', '{']) || line.starts_with([')', ']', '}']);
+        if !self.text.is_empty() && (self.in_string || !inside_bracket) {
+            self.text.push(' ');
+        }
+
+        for c in line.chars() {
+            if self.in_string {
+                if c == '"' {
+                    self.in_string = false;
+                }
+                continue;
+            }
+            match c {
+                '"' => self.in_string = true,
+                '(' | '[' | '{' => self.depth += 1,
+                ')' | ']' | '}' => self.depth -= 1,
+                _ => {}
+            }
+        }
+        self.text.push_str(line);
+    }
+
+    /// Returns false when the statement obviously continues on the next line:
+    /// it has unclosed brackets or string literals, or it ends on an operator
+    /// or comma.
+    fn is_complete(&self) -> bool {
+        let ends_on_operator = self
+            .text
+            .trim_end()
+            .ends_with(['+', '-', '*', '/', ',', '(', '{', '[']);
+        !self.in_string && self.depth <= 0 && !ends_on_operator
+    }
+
+    /// Has open brackets outside of any string literal.
+    fn is_unclosed(&self) -> bool {
+        !self.in_string && self.depth > 0
+    }
+
+    fn take(&mut self) -> String {
+        std::mem::take(self).text
+    }
+}
+
+const SPEC_FIELDS: &[&str] = &["function_call_format"];
+
+pub(crate) fn apply_spec(context: &mut Context, call: &FunctionCall) -> Result<(), String> {
+    let (field, values) = match call.args.split_first() {
+        Some((Expr::Identifier(field) | Expr::Literal(Literal::String(field)), values)) => {
+            (field.as_str(), values)
+        }
+        _ => return Err("first argument must be a field name".to_string()),
+    };
+    if !SPEC_FIELDS.contains(&field) {
+        return Err(format!(
+            "unknown field '{}', expected one of: {}",
+            field,
+            SPEC_FIELDS.join(", ")
+        ));
+    }
+
+    match values {
+        [
+            Expr::Literal(Literal::String(pattern)),
+            Expr::Literal(Literal::String(separator)),
+        ] => context.update_function_call_format(pattern.clone(), separator.clone()),
+        _ => Err(format!(
+            "{} expects a pattern string and a separator string",
+            field
+        )),
+    }
+}
+
+/// The outcome of parsing a program. Parsing recovers from a failing statement
+/// by recording the error and continuing with the next line, so `nodes` holds
+/// every statement that parsed and `errors` the `(line, error)` of the rest.
+/// `initial_context` is the context the first statement was parsed in, i.e.
+/// after the program header was applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseProgramResult {
+    pub nodes: Vec<Expr>,
+    pub errors: Vec<(usize, SpectreError)>,
+    pub initial_context: Context,
+}
+
+impl ParseProgramResult {
+    /// Strict view of the result: the nodes, or the first error if any.
+    pub fn into_result(self) -> Result<Vec<Expr>, SpectreError> {
+        match self.errors.into_iter().next() {
+            Some((_, error)) => Err(error),
+            None => Ok(self.nodes),
+        }
+    }
+}
+
+pub fn parse_program(input: &str) -> ParseProgramResult {
+    parse_program_with_context(input, Context::default())
+}
+
+/// Parses a whole program starting from `context` instead of the default one,
+/// e.g. a context restored with `Context::from_json`. A header or `SPEC`
+/// directive in the program still overrides it. The input is split into lines
+/// according to `context.line_ending`.
+pub fn parse_program_with_context(input: &str, context: Context) -> ParseProgramResult {
+    parse_program_with_options(input, context, ParserOptions::default())
+}
+
+/// Like `parse_program_with_context`, with explicit limits on the size of each
+/// statement. A statement over a limit is reported as an error.
+pub fn parse_program_with_options(
+    input: &str,
+    mut context: Context,
+    options: ParserOptions,
+) -> ParseProgramResult {
     // input here is the whole file
-    let mut context = Context::default();
-    let mut remainder = input;
+    let mut initial_context = context.clone();
+    let mut parser = ExpressionParser::new(&context, options);
+    let mut nodes = Vec::new();
+    let mut errors = Vec::new();
+    let mut pending = PendingStatement::default();
+    let mut statement_line = 0;
+    let mut seen_statement = false;
 
-    while !remainder.trim().is_empty() {
-        match parse_expression(&context, input) {
-            Ok(_) => todo!(),
-            Err(_) => todo!(),
+    let mut record_error = |line: usize, message: String| {
+        errors.push((line, SpectreError::Parse { line, message }));
+    };
+
+    // A statement that fails to parse is skipped as a whole, including the
+    // lines it swallowed; those are never re-parsed as statements. The only
+    // resync point inside an unclosed statement is a line that opens a call
+    // at column 0, which ends the unclosed statement with an error.
+    for (index, line) in context.line_ending.lines(input).into_iter().enumerate() {
+        let line_number = index + 1;
+        if pending.is_unclosed() && parser.starts_call(line) {
+            record_error(
+                statement_line,
+                format!("Unclosed statement starting on line {}", statement_line),
+            );
+            pending.take();
+        }
+        if pending.is_empty() {
+            statement_line = line_number;
+        }
+        // A line ending that doesn't match `context.line_ending` is not
+        // trimmed, so the statement containing it fails to parse.
+        pending
+            .push_line(line.trim_matches(|c: char| c.is_whitespace() && !matches!(c, '\r' | '\n')));
+
+        if !pending.is_complete() {
+            continue;
         }
+        let statement = pending.take();
+        if statement.is_empty() {
+            continue;
+        }
+
+        if !seen_statement && statement.starts_with('{') {
+            seen_statement = true;
+            match Context::from_program_header(&statement) {
+                Ok(header_context) => {
+                    initial_context = header_context.clone();
+                    context = header_context;
+                    parser = ExpressionParser::new(&context, options);
+                }
+                Err(e) => {
+                    record_error(
+                        statement_line,
+                        format!("Program header on line {}: {}", statement_line, e),
+                    );
+                }
+            }
+            continue;
+        }
+        seen_statement = true;
+
+        let expr = match parser.statement(&statement) {
+            Ok((_, expr)) => expr,
+            Err(e) => {
+                let reason = match e {
+                    nom::Err::Failure(e) if e.code == nom::error::ErrorKind::TooLarge => {
+                        format!("expression too deeply nested (limit {})", options.max_depth)
+                    }
+                    nom::Err::Failure(e) if e.code == nom::error::ErrorKind::Count => format!(
+                        "expression has too many nodes (limit {})",
+                        options.max_nodes
+                    ),
+                    _ => statement.clone(),
+                };
+                record_error(
+                    statement_line,
+                    format!("Failed to parse line {}: {}", statement_line, reason),
+                );
+                continue;
+            }
+        };
+
+        if let Expr::FunctionCall(call) = &expr
+            && call.name == "SPEC"
+        {
+            if let Err(e) = apply_spec(&mut context, call) {
+                record_error(
+                    statement_line,
+                    format!("SPEC on line {}: {}", statement_line, e),
+                );
+                continue;
+            }
+            parser = ExpressionParser::new(&context, options);
+        }
+        nodes.push(expr);
+    }
+    if !pending.is_empty() {
+        record_error(
+            statement_line,
+            format!(
+                "Unexpected end of input in statement starting on line {}",
+                statement_line
+            ),
+        );
+    }
+
+    ParseProgramResult {
+        nodes,
+        errors,
+        initial_context,
     }
-    todo!()
 }
 
 #[cfg(test)]
@@ -117,10 +536,10 @@ mod tests {
     fn test_parse_expression() {
         let function_call_format =
             FunctionCallFormat::new("NAME(ARGS)".to_string(), " ".to_string());
-        let mut context = Context::new(function_call_format);
+        let context = Context::new(function_call_format);
 
         let input = "foo(bar baz)";
-        let result = parse_expression(&mut context, input);
+        let result = parse_expression(&context, input);
 
         assert!(result.is_ok());
         let (rest, expr) = result.unwrap();
@@ -135,6 +554,194 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_nested_function_call() {
+        let function_call_format =
+            FunctionCallFormat::new("NAME(ARGS)".to_string(), ",".to_string());
+        let context = Context::new(function_call_format);
+
+        let (rest, expr) = parse_expression(&context, "foo(bar(1, 2), 3)").unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(
+            expr,
+            Expr::call("foo", [Expr::call("bar", [1.into(), 2.into()]), 3.into()])
+        );
+    }
+
+    // `depth` levels in total: `depth - 1` nested calls around a literal.
+    fn nested_calls(depth: usize) -> String {
+        format!("{}1{}", "(f ".repeat(depth - 1), ")".repeat(depth - 1))
+    }
+
+    #[test]
+    fn test_parse_nesting_limit() {
+        let max_depth = ParserOptions::default().max_depth;
+        let nodes = parse_program(&nested_calls(max_depth))
+            .into_result()
+            .unwrap();
+        assert_eq!(nodes[0].depth(), max_depth);
+
+        let result = parse_program(&nested_calls(max_depth + 1));
+        assert!(result.nodes.is_empty());
+        assert_eq!(
+            result.errors[0].1.to_string(),
+            format!(
+                "Failed to parse line 1: expression too deeply nested (limit {})",
+                max_depth
+            )
+        );
+
+        // Far past the limit is still an error rather than a stack overflow.
+        assert_eq!(parse_program(&nested_calls(max_depth * 10)).errors.len(), 1);
+    }
+
+    #[test]
+    fn test_parser_options() {
+        let options = ParserOptions {
+            max_depth: 3,
+            max_nodes: 5,
+        };
+        let parse = |input: &str| parse_program_with_options(input, Context::default(), options);
+
+        // depth 3, 5 nodes: exactly at both limits.
+        let nodes = parse("(f (g 1) 2 3)").into_result().unwrap();
+        assert_eq!((nodes[0].depth(), nodes[0].node_count()), (3, 5));
+
+        assert_eq!(
+            parse("(f (g (h 1)))")
+                .into_result()
+                .unwrap_err()
+                .to_string(),
+            "Failed to parse line 1: expression too deeply nested (limit 3)"
+        );
+        assert_eq!(
+            parse("(f 1 2 3 4 5)")
+                .into_result()
+                .unwrap_err()
+                .to_string(),
+            "Failed to parse line 1: expression has too many nodes (limit 5)"
+        );
+        // The node count starts over for each statement.
+        assert_eq!(parse("(f 1 2 3)\n(g 1 2 3)").nodes.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_numeric_literals() {
+        assert_eq!(parse_literal("42"), Ok(("", Literal::Integer(42))));
+        assert_eq!(parse_literal("-42"), Ok(("", Literal::Integer(-42))));
+        assert_eq!(parse_literal("3.25"), Ok(("", Literal::Float(3.25))));
+        assert_eq!(parse_literal("-0.5"), Ok(("", Literal::Float(-0.5))));
+        assert_eq!(parse_literal("2."), Ok(("", Literal::Float(2.0))));
+        assert_eq!(parse_literal(".5"), Ok(("", Literal::Float(0.5))));
+        assert_eq!(parse_literal("-.5"), Ok(("", Literal::Float(-0.5))));
+        assert_eq!(parse_literal("1.5.2"), Ok((".2", Literal::Float(1.5))));
+
+        assert!(parse_literal("-").is_err());
+        assert!(parse_literal(".").is_err());
+        assert!(parse_literal("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn test_parse_boolean_prefixed_identifiers() {
+        let context = Context::default();
+        let (rest, expr) =
+            parse_expression(&context, "(f true_value false_flag truex true false)").unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(
+            expr,
+            Expr::call(
+                "f",
+                [
+                    Expr::Identifier("true_value".to_string()),
+                    Expr::Identifier("false_flag".to_string()),
+                    Expr::Identifier("truex".to_string()),
+                    true.into(),
+                    false.into(),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_numeric_arguments() {
+        let context = Context::default();
+        let (_, expr) = parse_expression(&context, "(SPEC max_items 100 -1.5)").unwrap();
+        assert_eq!(
+            expr,
+            Expr::call(
+                "SPEC",
+                [
+                    Expr::Identifier("max_items".to_string()),
+                    100.into(),
+                    (-1.5).into()
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_unicode_identifiers() {
+        let function_call_format =
+            FunctionCallFormat::new("NAME(ARGS)".to_string(), ",".to_string());
+        let context = Context::new(function_call_format);
+
+        let (rest, expr) = parse_expression(&context, "σ(π, α_1, _β, größe)").unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(
+            expr,
+            Expr::call(
+                "σ",
+                ["π", "α_1", "_β", "größe"].map(|name| Expr::Identifier(name.to_string()))
+            )
+        );
+
+        assert!(parse_identifier("1x").is_err());
+    }
+
+    #[test]
+    fn test_parse_symbol_identifiers() {
+        for name in ["∑", "∏x", "x∑", "🙂", "√2"] {
+            assert_eq!(parse_identifier(name), Ok(("", name)));
+        }
+        assert!(parse_literal("true∑").is_err());
+        assert!(parse_identifier("+").is_err());
+        assert!(parse_identifier("1x").is_err());
+
+        let function_call_format =
+            FunctionCallFormat::new("NAME(ARGS)".to_string(), ",".to_string());
+        let context = Context::new(function_call_format);
+        let (rest, expr) = parse_expression(&context, "∑(1, 🙂, ∏)").unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(
+            expr,
+            Expr::call(
+                "∑",
+                [
+                    1.into(),
+                    Expr::Identifier("🙂".to_string()),
+                    Expr::Identifier("∏".to_string())
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_extract_args() {
+        let comma = Regex::new(r",\s*").unwrap();
+
+        assert_eq!(extract_args("bar(1), 2", &comma), vec!["bar(1)", "2"]);
+        assert_eq!(extract_args("[1, 2], 3", &comma), vec!["[1, 2]", "3"]);
+        assert_eq!(
+            extract_args(r#""a,b", "c""#, &comma),
+            vec![r#""a,b""#, r#""c""#]
+        );
+        assert_eq!(
+            extract_args("(f (g 1 2) 3) 4", &Regex::new(" ").unwrap()),
+            vec!["(f (g 1 2) 3)", "4"]
+        );
+        assert_eq!(extract_args("x", &comma), vec!["x"]);
+    }
+
     #[test]
     fn test_parse_program() {
         let input = r#"{"NAME(ARGS)" " "}
@@ -143,9 +750,14 @@ mod tests {
         bar:qux,quux"#;
 
         let result = parse_program(input);
-        assert!(result.is_ok());
+        assert!(result.errors.is_empty());
+        // The header applies from the start; the later SPEC does not.
+        assert_eq!(
+            result.initial_context,
+            Context::from_program_header(r#"{"NAME(ARGS)" " "}"#).unwrap()
+        );
 
-        let ast_nodes = result.unwrap();
+        let ast_nodes = result.nodes;
         assert_eq!(ast_nodes.len(), 3);
 
         // First node: foo(bar baz) with original syntax
@@ -175,6 +787,121 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_pending_statement_is_complete() {
+        let is_line_complete = |line: &str| {
+            let mut pending = PendingStatement::default();
+            pending.push_line(line);
+            pending.is_complete()
+        };
+        assert!(is_line_complete("foo(bar(1), 2)"));
+        assert!(is_line_complete(r#"foo(")")"#));
+        assert!(!is_line_complete("foo(bar(1),"));
+        assert!(!is_line_complete("foo(bar(1"));
+        assert!(!is_line_complete(r#"foo("a"#));
+        assert!(!is_line_complete("(foo 1 +"));
+    }
+
+    #[test]
+    fn test_parse_program_with_closing_bracket_on_own_line() {
+        let nodes = parse_program("(f\n  1\n  2\n)").into_result().unwrap();
+        assert_eq!(nodes, vec![Expr::call("f", [1.into(), 2.into()])]);
+
+        let nodes = parse_program("{\"NAME(ARGS)\" \",\"}\nf(\n  1,\n  2\n)")
+            .into_result()
+            .unwrap();
+        assert_eq!(nodes, vec![Expr::call("f", [1.into(), 2.into()])]);
+    }
+
+    #[test]
+    fn test_parse_program_with_long_continuation() {
+        let lines = 20_000;
+        let input = format!("(f{})", "\n  1".repeat(lines));
+        let nodes = parse_program(&input).into_result().unwrap();
+        assert_eq!(nodes[0].node_count(), lines + 1);
+    }
+
+    #[test]
+    fn test_parse_program_with_continuation() {
+        let input = r#"{"NAME(ARGS)" ",\s*"}
+        foo(bar(1,
+            2),
+            3)
+        baz(4)"#;
+
+        let ast_nodes = parse_program(input).into_result().unwrap();
+        assert_eq!(
+            ast_nodes,
+            vec![
+                Expr::call("foo", [Expr::call("bar", [1.into(), 2.into()]), 3.into()]),
+                Expr::call("baz", [4.into()]),
+            ]
+        );
+
+        let result = parse_program("(foo 1\n");
+        assert_eq!(result.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_infix_calls() {
+        let nodes = parse_program("{\"ARG1 NAME ARG2\" \" \"}\n1 plus x\n\"a\" concat \"b c\"")
+            .into_result()
+            .unwrap();
+        assert_eq!(
+            nodes,
+            vec![
+                Expr::call("plus", [1.into(), Expr::Identifier("x".to_string())]),
+                Expr::call(
+                    "concat",
+                    [Expr::from("a".to_string()), Expr::from("b c".to_string())]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_program_with_context() {
+        let context = Context::from_program_header(r#"{"NAME:ARGS" ","}"#).unwrap();
+        let ast_nodes = parse_program_with_context("foo:1,2", context).nodes;
+        assert_eq!(ast_nodes, vec![Expr::call("foo", [1.into(), 2.into()])]);
+    }
+
+    #[test]
+    fn test_parse_program_line_endings() {
+        let lf = "{\"NAME(ARGS)\" \",\"}\nfoo(1,\n2)\nbar(\"x\")\n";
+        let expected = parse_program(lf).into_result().unwrap();
+        assert_eq!(expected.len(), 2);
+
+        let crlf = lf.replace('\n', "\r\n");
+        let cr = lf.replace('\n', "\r");
+        assert_eq!(parse_program(&crlf).into_result().unwrap(), expected);
+        assert_eq!(parse_program(&cr).into_result().unwrap(), expected);
+
+        let context = Context {
+            line_ending: LineEnding::Crlf,
+            ..Context::default()
+        };
+        assert_eq!(
+            parse_program_with_context(&crlf, context.clone()).nodes,
+            expected
+        );
+        // A lone `\r` is not a line break in CRLF mode.
+        assert!(!parse_program_with_context(&cr, context).errors.is_empty());
+
+        let context = Context {
+            line_ending: LineEnding::Lf,
+            ..Context::default()
+        };
+        assert_eq!(
+            parse_program_with_context(lf, context.clone()).nodes,
+            expected
+        );
+        let result = parse_program_with_context(&crlf, context.clone());
+        assert!(result.nodes.is_empty());
+        assert_eq!(result.errors.len(), 2);
+        assert!(!parse_program_with_context(&cr, context).errors.is_empty());
+    }
+
     #[test]
     fn test_parse_invalid_program() {
         let input = r#"{"NAME(ARGS)" ",\s*"}
@@ -184,6 +911,128 @@ mod tests {
         "#;
 
         let result = parse_program(input);
-        assert!(result.is_err());
+        assert_eq!(result.nodes.len(), 2);
+        assert_eq!(
+            result.errors,
+            vec![(
+                4,
+                SpectreError::Parse {
+                    line: 4,
+                    message: "Failed to parse line 4: foo(bar, baz)".to_string(),
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_parse_program_recovers_after_errors() {
+        let input = r#"(foo 1)
+        foo(1)
+        (bar 1x)
+        (SPEC function_call_format "NAME" " ")
+        (baz 2)"#;
+
+        let result = parse_program(input);
+        assert_eq!(
+            result.nodes,
+            vec![Expr::call("foo", [1.into()]), Expr::call("baz", [2.into()])]
+        );
+        let error_lines: Vec<usize> = result.errors.iter().map(|(line, _)| *line).collect();
+        assert_eq!(error_lines, vec![2, 3, 4]);
+        assert_eq!(
+            result.errors[2].1.to_string(),
+            "SPEC on line 4: pattern must contain exactly one ARGS placeholder, found 0"
+        );
+    }
+
+    #[test]
+    fn test_parse_program_recovers_from_unclosed_statements() {
+        let error_lines = |result: &ParseProgramResult| -> Vec<usize> {
+            result.errors.iter().map(|(line, _)| *line).collect()
+        };
+
+        // A call opened at column 0 ends the unclosed statement before it.
+        let result = parse_program("(foo 1\n(bar 2)\n(baz 3)\n(qux 4)");
+        assert_eq!(
+            result.nodes,
+            vec![
+                Expr::call("bar", [2.into()]),
+                Expr::call("baz", [3.into()]),
+                Expr::call("qux", [4.into()]),
+            ]
+        );
+        assert_eq!(
+            result.errors,
+            vec![(
+                1,
+                SpectreError::Parse {
+                    line: 1,
+                    message: "Unclosed statement starting on line 1".to_string(),
+                }
+            )]
+        );
+
+        // Swallowed lines are skipped with the failed statement, never
+        // re-parsed on their own.
+        let result = parse_program("(f 1\n   (g 2)\n   x!)");
+        assert_eq!(result.nodes, vec![]);
+        assert_eq!(error_lines(&result), vec![1]);
+
+        let result = parse_program("(foo \"a\n(bar 2)\n\"x)\n(baz 3)");
+        assert_eq!(result.nodes, vec![Expr::call("baz", [3.into()])]);
+        assert_eq!(error_lines(&result), vec![1]);
+
+        let result = parse_program("(f 1\n)");
+        assert_eq!(result.nodes, vec![Expr::call("f", [1.into()])]);
+        assert_eq!(result.errors, vec![]);
+
+        // Large unclosed inputs are handled in a single pass.
+        let result = parse_program(&"(f 1\n".repeat(5_000));
+        assert_eq!(result.nodes, vec![]);
+        assert_eq!(error_lines(&result), (1..=5_000).collect::<Vec<_>>());
+
+        let result = parse_program(&format!("(f 1\n{}", "  (g 1\n".repeat(5_000)));
+        assert_eq!(result.nodes, vec![]);
+        assert_eq!(
+            result.errors,
+            vec![(
+                1,
+                SpectreError::Parse {
+                    line: 1,
+                    message: "Unexpected end of input in statement starting on line 1".to_string(),
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_spec() {
+        let error_message =
+            |input: &str| parse_program(input).into_result().unwrap_err().to_string();
+
+        assert_eq!(
+            error_message(
+                r#"{"NAME(ARGS)" ",\s*"}
+                foo(bar)
+                SPEC(function_call_format, "NAME:NAME", ",")"#
+            ),
+            "SPEC on line 3: pattern must contain exactly one NAME placeholder, found 2"
+        );
+        assert!(
+            error_message(r#"(SPEC "function_call_format" "(NAME ARGS" "(")"#)
+                .starts_with("SPEC on line 1: separator is not a valid regular expression")
+        );
+        assert_eq!(
+            error_message(r#"(SPEC function_def_format "NAME ARGS" " ")"#),
+            "SPEC on line 1: unknown field 'function_def_format', expected one of: function_call_format"
+        );
+        assert_eq!(
+            error_message(r#"(SPEC function_call_format "NAME ARGS")"#),
+            "SPEC on line 1: function_call_format expects a pattern string and a separator string"
+        );
+        assert_eq!(
+            error_message(r#"{"NAME" ","}"#),
+            "Program header on line 1: pattern must contain exactly one ARGS placeholder, found 0"
+        );
     }
 }
@@ -0,0 +1,44 @@
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_watch_reruns_on_change() {
+    let path = std::env::temp_dir().join(format!("spectre_watch_{}.spec", std::process::id()));
+    fs::write(&path, "(foo 1)\n").unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_spectre"))
+        .arg("--watch")
+        .arg(&path)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let (sender, receiver) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else { break };
+            if line.contains("(re-run at") {
+                let _ = sender.send(line);
+            }
+        }
+    });
+
+    let first = receiver.recv_timeout(Duration::from_secs(10));
+    // Give the watcher time to start before changing the file.
+    thread::sleep(Duration::from_millis(500));
+    fs::write(&path, "(foo 2)\n").unwrap();
+    let second = receiver.recv_timeout(Duration::from_secs(10));
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    // Re-runs are preceded by the escape sequence that clears the screen.
+    assert!(first.unwrap().starts_with("=== "));
+    assert!(second.unwrap().contains("=== "));
+}